@@ -4,7 +4,7 @@ use clap::Parser;
 use scoped_threadpool::Pool;
 use std::cell::RefCell;
 use std::fs::{self, File};
-use std::os::unix::fs::{FileExt, MetadataExt};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug, Clone)]
@@ -20,6 +20,22 @@ struct Args {
     /// fill zero when the read length is less than expected.
     #[arg(long, default_value_t = false)]
     fill_zero: bool,
+
+    /// read CRC32C sums from the files and check them, instead of computing new ones.
+    #[arg(short, long, default_value_t = false)]
+    check: bool,
+
+    /// don't print OK for each successfully verified file.
+    #[arg(long, default_value_t = false)]
+    quiet: bool,
+
+    /// recurse into directories, checksumming every regular file found.
+    #[arg(short, long, default_value_t = false)]
+    recursive: bool,
+
+    /// follow symlinks instead of skipping them.
+    #[arg(long, default_value_t = false)]
+    follow_symlinks: bool,
 }
 
 const BLOCK_SIZE: usize = 16 << 20; // 16MiB.
@@ -28,13 +44,34 @@ thread_local! {
     static TLS: RefCell<AlignedBytes> = RefCell::new(AlignedBytes::new_zeroed(BLOCK_SIZE, ALIGN_SIZE));
 }
 
+#[cfg(unix)]
+fn positional_read(file: &File, _path: &Path, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+// `seek_read` moves the handle's cursor, so sharing `file` across threads
+// would race; open a fresh handle per call instead.
+#[cfg(windows)]
+fn positional_read(_file: &File, path: &Path, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    fs::File::open(path)?.seek_read(buf, offset)
+}
+
+#[cfg(unix)]
+fn file_len(file: &File) -> std::io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(file.metadata()?.size())
+}
+#[cfg(windows)]
+fn file_len(file: &File) -> std::io::Result<u64> {
+    Ok(file.metadata()?.len())
+}
+
 fn parallel_read(file: &File, path: &Path, pool: &mut Pool, fill_zero: bool) -> Result<u32> {
     let mut start = 0u64;
     let mut crc32c = 0u32;
-    let file_size = file
-        .metadata()
-        .with_context(|| format!("get file metadata failed: {}", path.display()))?
-        .size();
+    let file_size = file_len(file)
+        .with_context(|| format!("get file metadata failed: {}", path.display()))?;
     loop {
         let mut vec: Vec<Result<(u64, u32, bool)>> = vec![];
         vec.resize_with(pool.thread_count() as usize, || Ok((0, 0, true)));
@@ -45,7 +82,7 @@ fn parallel_read(file: &File, path: &Path, pool: &mut Pool, fill_zero: bool) ->
                     let offset = start + (i * BLOCK_SIZE) as u64;
                     *r = TLS.with(|v| -> Result<(u64, u32, bool)> {
                         let mut buf = v.borrow_mut();
-                        let mut n = file.read_at(&mut buf, offset).with_context(|| {
+                        let mut n = positional_read(file, path, &mut buf, offset).with_context(|| {
                             format!("read source file failed: {}", path.display())
                         })?;
                         if fill_zero && offset < file_size {
@@ -99,31 +136,354 @@ fn open(path: &Path) -> std::io::Result<fs::File> {
     fs::File::open(path)
 }
 
+/// Splits on the first run of whitespace, so paths may contain spaces.
+fn split_manifest_line(line: &str) -> Option<(&str, &str)> {
+    let digest_end = line.find(char::is_whitespace)?;
+    let (digest, rest) = line.split_at(digest_end);
+    let path = rest.trim_start();
+    if path.is_empty() {
+        None
+    } else {
+        Some((digest, path))
+    }
+}
+
+fn verify_manifest(manifest: &Path, pool: &mut Pool, fill_zero: bool, quiet: bool) -> Result<u64> {
+    let content = fs::read_to_string(manifest)
+        .with_context(|| format!("Failed to open manifest {}", manifest.display()))?;
+    let mut failed = 0u64;
+    for (i, line) in content.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let Some((digest, path)) = split_manifest_line(line) else {
+            eprintln!(
+                "{}: {}: malformed line, expected '<digest> <path>'",
+                manifest.display(),
+                i + 1
+            );
+            failed += 1;
+            continue;
+        };
+        let Some(expected) = (digest.len() == 8 && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| u32::from_str_radix(digest, 16).ok())
+            .flatten()
+        else {
+            eprintln!(
+                "{}: {}: invalid digest '{}', expected 8 hex digits",
+                manifest.display(),
+                i + 1,
+                digest
+            );
+            failed += 1;
+            continue;
+        };
+
+        let path = Path::new(path);
+        let actual = open(path)
+            .with_context(|| format!("Failed to open file {}", path.display()))
+            .and_then(|file| parallel_read(&file, path, pool, fill_zero));
+
+        match actual {
+            Ok(actual) if actual == expected => {
+                if !quiet {
+                    println!("{}: OK", path.display());
+                }
+            }
+            Ok(_) => {
+                println!("{}: FAILED", path.display());
+                failed += 1;
+            }
+            Err(err) => {
+                println!("{}: FAILED open or read ({err})", path.display());
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed)
+}
+
+/// Expands directories into their files when `recursive` is set.
+fn collect_files(paths: &[PathBuf], recursive: bool, follow_symlinks: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_path(path, recursive, follow_symlinks, &mut files)?;
+    }
+    Ok(files)
+}
+
+fn collect_path(
+    path: &Path,
+    recursive: bool,
+    follow_symlinks: bool,
+    out: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to stat {}", path.display()))?;
+
+    if metadata.file_type().is_symlink() {
+        if !follow_symlinks {
+            eprintln!("{}: skipping symlink", path.display());
+            return Ok(());
+        }
+        let target = fs::metadata(path)
+            .with_context(|| format!("Failed to stat {}", path.display()))?;
+        return classify(path, &target, recursive, follow_symlinks, out);
+    }
+
+    classify(path, &metadata, recursive, follow_symlinks, out)
+}
+
+fn classify(
+    path: &Path,
+    metadata: &fs::Metadata,
+    recursive: bool,
+    follow_symlinks: bool,
+    out: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if metadata.is_dir() {
+        if !recursive {
+            eprintln!("{}: is a directory, use -r to recurse into it", path.display());
+            return Ok(());
+        }
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory {}", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()
+            .with_context(|| format!("Failed to read directory {}", path.display()))?;
+        entries.sort();
+        for entry in entries {
+            collect_path(&entry, recursive, follow_symlinks, out)?;
+        }
+    } else if metadata.is_file() {
+        out.push(path.to_path_buf());
+    } else {
+        eprintln!("{}: skipping special file", path.display());
+    }
+    Ok(())
+}
+
+/// Raises the soft `RLIMIT_NOFILE` towards the hard limit.
+#[cfg(unix)]
+fn raise_fd_limit() {
+    unsafe {
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) == 0 {
+            limit.rlim_cur = limit.rlim_max;
+            libc::setrlimit(libc::RLIMIT_NOFILE, &limit);
+        }
+    }
+}
+#[cfg(not(unix))]
+fn raise_fd_limit() {}
+
+struct OpenedFile<'a> {
+    file: File,
+    path: &'a Path,
+    size: u64,
+}
+
+fn block_count(size: u64) -> usize {
+    (size.div_ceil(BLOCK_SIZE as u64)).max(1) as usize
+}
+
+/// Splits every path into `BLOCK_SIZE` blocks and hands each block to `pool`
+/// as its own task, so a large file doesn't pin one worker while the rest
+/// sit idle; per-file results are combined from their blocks in order.
+fn hash_files(paths: &[PathBuf], pool: &mut Pool, fill_zero: bool) -> Vec<Result<u32>> {
+    let opened: Vec<Result<OpenedFile>> = paths
+        .iter()
+        .map(|path| -> Result<OpenedFile> {
+            let file =
+                open(path).with_context(|| format!("Failed to open file {}", path.display()))?;
+            let size = file_len(&file)
+                .with_context(|| format!("get file metadata failed: {}", path.display()))?;
+            Ok(OpenedFile { file, path, size })
+        })
+        .collect();
+
+    let tasks: Vec<(&File, &Path, u64, u64)> = opened
+        .iter()
+        .flatten()
+        .flat_map(|o| {
+            (0..block_count(o.size))
+                .map(move |block| (&o.file, o.path, (block as u64) * BLOCK_SIZE as u64, o.size))
+        })
+        .collect();
+
+    let mut block_results: Vec<Result<(u32, u32)>> = (0..tasks.len()).map(|_| Ok((0, 0))).collect();
+    pool.scoped(|scoped| {
+        for (&(file, path, offset, file_size), result) in tasks.iter().zip(block_results.iter_mut()) {
+            scoped.execute(move || {
+                *result = TLS.with(|v| -> Result<(u32, u32)> {
+                    let mut buf = v.borrow_mut();
+                    let mut n = positional_read(file, path, &mut buf, offset).with_context(|| {
+                        format!("read source file failed: {}", path.display())
+                    })?;
+                    if fill_zero && offset < file_size {
+                        let expect = std::cmp::min(file_size - offset, BLOCK_SIZE as u64) as usize;
+                        if n < expect {
+                            buf[n..expect].fill(0);
+                            n = expect;
+                        }
+                    }
+                    Ok((n as u32, crc32c::crc32c(&buf[..n])))
+                });
+            });
+        }
+    });
+
+    let mut blocks = block_results.into_iter();
+    opened
+        .into_iter()
+        .map(|o| -> Result<u32> {
+            let o = o?;
+            let mut crc32c = 0u32;
+            for result in blocks.by_ref().take(block_count(o.size)) {
+                let (n, crc) = result?;
+                crc32c = crc32c::crc32c_combine(crc32c, crc, n as usize);
+            }
+            Ok(crc32c)
+        })
+        .collect()
+}
+
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).with_context(|| "read stdin failed")? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn hash_stdin(pool: &mut Pool) -> Result<u32> {
+    let thread_count = pool.thread_count() as usize;
+    let mut buffers: Vec<AlignedBytes> = (0..thread_count)
+        .map(|_| AlignedBytes::new_zeroed(BLOCK_SIZE, ALIGN_SIZE))
+        .collect();
+    let mut lens = vec![0usize; thread_count];
+    let mut crc32c = 0u32;
+
+    let stdin = std::io::stdin();
+    let mut lock = stdin.lock();
+    loop {
+        let mut eof = false;
+        for (i, buf) in buffers.iter_mut().enumerate() {
+            if eof {
+                lens[i] = 0;
+                continue;
+            }
+            lens[i] = read_block(&mut lock, &mut buf[..])?;
+            if lens[i] < BLOCK_SIZE {
+                eof = true;
+            }
+        }
+
+        let mut results: Vec<(usize, u32)> = vec![(0, 0); buffers.len()];
+        pool.scoped(|scoped| {
+            for ((buf, &len), r) in buffers.iter().zip(lens.iter()).zip(results.iter_mut()) {
+                scoped.execute(move || {
+                    *r = (len, crc32c::crc32c(&buf[..len]));
+                });
+            }
+        });
+
+        for (len, crc) in results {
+            if len == 0 {
+                break;
+            }
+            crc32c = crc32c::crc32c_combine(crc32c, crc, len);
+        }
+
+        if eof {
+            return Ok(crc32c);
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
     let mut pool = Pool::new(args.threads);
 
-    for path in &args.paths {
-        let file = open(path).with_context(|| format!("Failed to open file {}", path.display()))?;
-        let crc32c = parallel_read(&file, path, &mut pool, args.fill_zero)?;
-        println!("{:08X} {}", crc32c, path.display());
+    if args.check {
+        let mut failed = 0u64;
+        for manifest in &args.paths {
+            failed += verify_manifest(manifest, &mut pool, args.fill_zero, args.quiet)?;
+        }
+        if failed > 0 {
+            println!("{failed} file(s) failed the checksum");
+            std::process::exit(1);
+        }
+        return Ok(());
     }
 
-    if args.paths.is_empty() {
-        let mut crc32c = 0;
-        let mut line = String::new();
-        loop {
-            // read from stdin.
-            let n = std::io::stdin()
-                .read_line(&mut line)
-                .with_context(|| "read stdin failed")?;
-            if n == 0 {
-                break;
-            }
-            crc32c = crc32c::crc32c_append(crc32c, line.as_bytes());
+    let paths = collect_files(&args.paths, args.recursive, args.follow_symlinks)?;
+    if !paths.is_empty() {
+        raise_fd_limit();
+        for (path, result) in paths.iter().zip(hash_files(&paths, &mut pool, args.fill_zero)) {
+            println!("{:08X} {}", result?, path.display());
         }
+    }
+
+    if args.paths.is_empty() {
+        let crc32c = hash_stdin(&mut pool)?;
         println!("{:08X} -", crc32c);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_manifest_line_basic() {
+        assert_eq!(
+            split_manifest_line("CAFEBABE path/to/file"),
+            Some(("CAFEBABE", "path/to/file"))
+        );
+    }
+
+    #[test]
+    fn split_manifest_line_tab_separator() {
+        assert_eq!(
+            split_manifest_line("cafebabe\tfile.bin"),
+            Some(("cafebabe", "file.bin"))
+        );
+    }
+
+    #[test]
+    fn split_manifest_line_collapses_a_run_of_whitespace() {
+        assert_eq!(
+            split_manifest_line("CAFEBABE    file.bin"),
+            Some(("CAFEBABE", "file.bin"))
+        );
+    }
+
+    #[test]
+    fn split_manifest_line_keeps_spaces_in_the_path() {
+        assert_eq!(
+            split_manifest_line("CAFEBABE my file.txt"),
+            Some(("CAFEBABE", "my file.txt"))
+        );
+    }
+
+    #[test]
+    fn split_manifest_line_trailing_whitespace_with_no_path() {
+        assert_eq!(split_manifest_line("CAFEBABE   "), None);
+    }
+
+    #[test]
+    fn split_manifest_line_no_whitespace() {
+        assert_eq!(split_manifest_line("CAFEBABE"), None);
+    }
+
+    #[test]
+    fn split_manifest_line_empty() {
+        assert_eq!(split_manifest_line(""), None);
+    }
+}